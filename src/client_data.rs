@@ -0,0 +1,82 @@
+//! Client data enrichment.
+//!
+//! `ClientData` captures what we know about the browser that submitted a
+//! report: its IP address, User-Agent string, the browser/OS/device
+//! information we can derive from it, and its geographic location.
+
+use fastly::geo::geo_lookup;
+use fastly::Error;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+mod ua;
+
+/// Information about the downstream client that submitted a report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientData {
+    /// The client's IP address.
+    ip: IpAddr,
+    /// The raw `User-Agent` header sent by the client.
+    user_agent: String,
+    /// The browser name parsed from `user_agent`, e.g. `"Chrome"`.
+    client_browser_name: String,
+    /// The browser version parsed from `user_agent`, e.g. `"115.0"`.
+    client_browser_version: String,
+    /// The OS name parsed from `user_agent`, e.g. `"Windows"`.
+    client_os_name: String,
+    /// The OS version parsed from `user_agent`, e.g. `"10"`.
+    client_os_version: String,
+    /// The device type parsed from `user_agent`: `"desktop"`, `"mobile"`,
+    /// `"tablet"`, or `"unknown"`.
+    client_device_type: String,
+    /// ISO 3166-1 country code, e.g. `"US"`.
+    client_country_code: Option<String>,
+    /// Continent code, e.g. `"NA"`.
+    client_continent: Option<String>,
+    /// Region/subdivision code, e.g. `"CA"`.
+    client_region: Option<String>,
+    /// City name.
+    client_city: Option<String>,
+    /// Autonomous system number the client IP belongs to.
+    client_as_number: Option<u32>,
+    /// Autonomous system organisation name.
+    client_as_name: Option<String>,
+    /// Estimated connection speed, e.g. `"broadband"`.
+    client_connection_speed: Option<String>,
+    /// Connection type, e.g. `"wifi"` or `"cellular"`.
+    client_connection_type: Option<String>,
+}
+
+impl ClientData {
+    /// Construct a new `ClientData` from the downstream client IP and
+    /// User-Agent header.
+    ///
+    /// The User-Agent is parsed into structured browser/OS/device fields
+    /// on a best-effort basis; a parse miss never fails the report, it
+    /// just yields `"unknown"` for the fields we couldn't determine.
+    /// Likewise, geo fields are looked up from the client IP and left as
+    /// `None` when Fastly has no geo data for it, rather than failing the
+    /// report.
+    pub fn new(ip: IpAddr, user_agent: &str) -> Result<ClientData, Error> {
+        let parsed = ua::parse(user_agent);
+        let geo = geo_lookup(ip);
+
+        Ok(ClientData {
+            ip,
+            user_agent: user_agent.to_owned(),
+            client_browser_name: parsed.browser_name,
+            client_browser_version: parsed.browser_version,
+            client_os_name: parsed.os_name,
+            client_os_version: parsed.os_version,
+            client_device_type: parsed.device_type,
+            client_country_code: geo.as_ref().map(|g| g.country_code().to_owned()),
+            client_continent: geo.as_ref().map(|g| g.continent().to_owned()),
+            client_region: geo.as_ref().and_then(|g| g.region()).map(str::to_owned),
+            client_city: geo.as_ref().map(|g| g.city().to_owned()),
+            client_as_number: geo.as_ref().map(|g| g.as_number()),
+            client_as_name: geo.as_ref().map(|g| g.as_name().to_owned()),
+            client_connection_speed: geo.as_ref().map(|g| g.conn_speed().to_owned()),
+            client_connection_type: geo.as_ref().map(|g| g.conn_type().to_owned()),
+        })
+    }
+}
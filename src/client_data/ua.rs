@@ -0,0 +1,167 @@
+//! A small, dependency-free User-Agent parser.
+//!
+//! This isn't trying to be exhaustive like `woothee` or `uap-core` — it
+//! covers the browser/OS/device combinations we actually see in report
+//! traffic. Anything it can't confidently identify comes back as
+//! `"unknown"` rather than failing, since a report is still worth logging
+//! even without a fully resolved UA.
+
+/// The structured fields we derive from a User-Agent string.
+pub struct UserAgentInfo {
+    pub browser_name: String,
+    pub browser_version: String,
+    pub os_name: String,
+    pub os_version: String,
+    pub device_type: String,
+}
+
+const UNKNOWN: &str = "unknown";
+
+/// A `(name, token)` pair tried in order; the first token found in the UA
+/// string wins. Order matters: e.g. Edge and Opera also include "Chrome"
+/// in their UA strings, so they must be checked first.
+const BROWSER_RULES: &[(&str, &str)] = &[
+    ("Edge", "Edg/"),
+    ("Opera", "OPR/"),
+    ("Samsung Internet", "SamsungBrowser/"),
+    ("Chrome", "Chrome/"),
+    ("Firefox", "Firefox/"),
+    ("Safari", "Version/"),
+    ("Internet Explorer", "MSIE "),
+    ("Internet Explorer", "Trident/"),
+];
+
+const OS_RULES: &[(&str, &str)] = &[
+    ("iOS", "iPhone OS "),
+    ("iOS", "CPU OS "),
+    ("Android", "Android "),
+    ("Windows", "Windows NT "),
+    ("macOS", "Mac OS X "),
+    ("Chrome OS", "CrOS "),
+    ("Linux", "Linux"),
+];
+
+/// Parse a User-Agent string into structured browser/OS/device fields.
+///
+/// Fields that can't be determined are set to `"unknown"`.
+pub fn parse(user_agent: &str) -> UserAgentInfo {
+    let (browser_name, browser_version) = BROWSER_RULES
+        .iter()
+        .find_map(|(name, token)| {
+            user_agent
+                .find(token)
+                .map(|i| (name.to_string(), version_after(&user_agent[i + token.len()..])))
+        })
+        .unwrap_or_else(|| (UNKNOWN.to_string(), UNKNOWN.to_string()));
+
+    let (os_name, os_version) = OS_RULES
+        .iter()
+        .find_map(|(name, token)| {
+            user_agent.find(token).map(|i| {
+                let version = version_after(&user_agent[i + token.len()..]).replace('_', ".");
+                (name.to_string(), version)
+            })
+        })
+        .unwrap_or_else(|| (UNKNOWN.to_string(), UNKNOWN.to_string()));
+
+    let device_type = if user_agent.contains("iPad") || user_agent.contains("Tablet") {
+        "tablet"
+    } else if user_agent.contains("Mobile") || user_agent.contains("Android") {
+        "mobile"
+    } else if os_name == UNKNOWN {
+        UNKNOWN
+    } else {
+        "desktop"
+    }
+    .to_string();
+
+    UserAgentInfo {
+        browser_name,
+        browser_version,
+        os_name,
+        os_version,
+        device_type,
+    }
+}
+
+/// Extract the leading `major.minor.patch`-style version token from the
+/// start of `rest`, stopping at the first separator that isn't a digit, a
+/// dot, or an underscore (iOS/macOS write versions like `16_5`, which
+/// callers normalize to `16.5` by replacing `_` with `.`).
+fn version_after(rest: &str) -> String {
+    let version: String = rest
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == '_')
+        .collect();
+
+    if version.is_empty() {
+        UNKNOWN.to_string()
+    } else {
+        version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_chrome_on_windows_desktop() {
+        let ua = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
+                  (KHTML, like Gecko) Chrome/115.0.0.0 Safari/537.36";
+        let info = parse(ua);
+
+        assert_eq!(info.browser_name, "Chrome");
+        assert_eq!(info.browser_version, "115.0.0.0");
+        assert_eq!(info.os_name, "Windows");
+        assert_eq!(info.os_version, "10.0");
+        assert_eq!(info.device_type, "desktop");
+    }
+
+    #[test]
+    fn parses_safari_on_ios_mobile_and_normalizes_underscored_version() {
+        let ua = "Mozilla/5.0 (iPhone; CPU iPhone OS 16_5 like Mac OS X) \
+                  AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.5 \
+                  Mobile/15E148 Safari/604.1";
+        let info = parse(ua);
+
+        assert_eq!(info.browser_name, "Safari");
+        assert_eq!(info.browser_version, "16.5");
+        assert_eq!(info.os_name, "iOS");
+        assert_eq!(info.os_version, "16.5");
+        assert_eq!(info.device_type, "mobile");
+    }
+
+    #[test]
+    fn parses_chrome_on_android_mobile() {
+        let ua = "Mozilla/5.0 (Linux; Android 13; Pixel 7) AppleWebKit/537.36 \
+                  (KHTML, like Gecko) Chrome/115.0.0.0 Mobile Safari/537.36";
+        let info = parse(ua);
+
+        assert_eq!(info.browser_name, "Chrome");
+        assert_eq!(info.os_name, "Android");
+        assert_eq!(info.os_version, "13");
+        assert_eq!(info.device_type, "mobile");
+    }
+
+    #[test]
+    fn prefers_edge_over_chrome_token_in_its_own_user_agent() {
+        let ua = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
+                  (KHTML, like Gecko) Chrome/115.0.0.0 Safari/537.36 Edg/115.0.1901.183";
+        let info = parse(ua);
+
+        assert_eq!(info.browser_name, "Edge");
+        assert_eq!(info.browser_version, "115.0.1901.183");
+    }
+
+    #[test]
+    fn falls_back_to_unknown_for_an_unrecognised_user_agent() {
+        let info = parse("SomeBot/1.0");
+
+        assert_eq!(info.browser_name, UNKNOWN);
+        assert_eq!(info.browser_version, UNKNOWN);
+        assert_eq!(info.os_name, UNKNOWN);
+        assert_eq!(info.os_version, UNKNOWN);
+        assert_eq!(info.device_type, UNKNOWN);
+    }
+}
@@ -0,0 +1,223 @@
+//! Sampling and outlier filtering for incoming reports.
+//!
+//! At NEL scale a single popular page can flood the logging endpoint with
+//! near-duplicate reports. This module runs over a parsed batch before
+//! `LogLine`s are built, applying three independent passes: a type
+//! allow-list, a stale-`age` cutoff, and a deterministic sampling rate.
+
+use crate::report::{Report, ReportBody};
+use fastly::Dictionary;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Name of the Dictionary holding tunable filter settings, so the rates
+/// below can be adjusted without a redeploy. Provisioning it is optional:
+/// if the service has no `reports_config` Dictionary configured, `load`
+/// falls back to the defaults below instead of failing the request.
+const CONFIG_DICTIONARY: &str = "reports_config";
+
+/// Default 1-in-N sampling rate: about one in every `DEFAULT_SAMPLE_RATE`
+/// distinct URL, type and phase combinations is kept, and every report
+/// sharing a kept combination is kept with it.
+const DEFAULT_SAMPLE_RATE: u64 = 1;
+
+/// Default maximum `age`, in milliseconds, before a report is treated as a
+/// stale outlier and dropped (mirrors Sentry's 180s mobile cutoff).
+const DEFAULT_MAX_AGE_MS: i64 = 180_000;
+
+/// Default allowed report types. Empty means "allow everything".
+const DEFAULT_ALLOWED_TYPES: &[&str] = &[];
+
+/// Filter a batch of reports, dropping disallowed types, stale outliers,
+/// and anything sampled out, before they're turned into log lines.
+pub fn filter_reports(reports: Vec<Report>) -> Vec<Report> {
+    let config = FilterConfig::load();
+
+    reports
+        .into_iter()
+        .filter(|report| config.is_allowed_type(report))
+        .filter(|report| config.is_within_max_age(report))
+        .filter(|report| config.is_sampled(report))
+        .collect()
+}
+
+/// Tunable filter settings, read from the `reports_config` Dictionary
+/// when present and falling back to the defaults above otherwise.
+struct FilterConfig {
+    sample_rate: u64,
+    max_age_ms: i64,
+    allowed_types: Vec<String>,
+}
+
+impl FilterConfig {
+    fn load() -> FilterConfig {
+        // `try_open` rather than `open`: a service that hasn't provisioned
+        // `reports_config` should fall back to the defaults below, not
+        // fail every request that reaches the filter.
+        let store = Dictionary::try_open(CONFIG_DICTIONARY).ok();
+
+        let sample_rate = store
+            .as_ref()
+            .and_then(|store| store.get("sample_rate"))
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_SAMPLE_RATE)
+            .max(1);
+
+        let max_age_ms = store
+            .as_ref()
+            .and_then(|store| store.get("max_age_ms"))
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_MAX_AGE_MS);
+
+        let allowed_types = store
+            .as_ref()
+            .and_then(|store| store.get("allowed_types"))
+            .map(|value| value.split(',').map(|s| s.trim().to_owned()).collect())
+            .unwrap_or_else(|| DEFAULT_ALLOWED_TYPES.iter().map(|s| s.to_string()).collect());
+
+        FilterConfig {
+            sample_rate,
+            max_age_ms,
+            allowed_types,
+        }
+    }
+
+    /// Whether `report`'s type passes the allow-list (an empty allow-list
+    /// permits every type).
+    fn is_allowed_type(&self, report: &Report) -> bool {
+        self.allowed_types.is_empty()
+            || self.allowed_types.iter().any(|t| t == &report.report_type)
+    }
+
+    /// Whether `report`'s `age` is recent enough to still be useful.
+    fn is_within_max_age(&self, report: &Report) -> bool {
+        report.age <= self.max_age_ms
+    }
+
+    /// Deterministically keep or drop `report` based on a hash of its URL,
+    /// type and phase, so the same error streams consistently rather than
+    /// flickering between requests.
+    fn is_sampled(&self, report: &Report) -> bool {
+        if self.sample_rate <= 1 {
+            return true;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        report.url.hash(&mut hasher);
+        report.report_type.hash(&mut hasher);
+        phase_of(report).hash(&mut hasher);
+
+        hasher.finish() % self.sample_rate == 0
+    }
+}
+
+/// The NEL `phase` of a report, or `""` for report types that don't have
+/// one.
+fn phase_of(report: &Report) -> &str {
+    match &report.body {
+        ReportBody::NetworkError(body) => body.phase.as_str(),
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::NetworkErrorBody;
+
+    fn network_error(url: &str, age: i64, phase: &str) -> Report {
+        Report {
+            age,
+            report_type: "network-error".to_string(),
+            url: url.to_string(),
+            user_agent: "test-agent".to_string(),
+            body: ReportBody::NetworkError(NetworkErrorBody {
+                referrer: "".to_string(),
+                sampling_fraction: 1.0,
+                server_ip: "127.0.0.1".to_string(),
+                protocol: "h2".to_string(),
+                method: "GET".to_string(),
+                status_code: 0,
+                elapsed_time: 100,
+                phase: phase.to_string(),
+                failure_type: "tcp.refused".to_string(),
+            }),
+        }
+    }
+
+    fn config(sample_rate: u64, max_age_ms: i64, allowed_types: &[&str]) -> FilterConfig {
+        FilterConfig {
+            sample_rate: sample_rate.max(1),
+            max_age_ms,
+            allowed_types: allowed_types.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn allows_everything_when_allow_list_is_empty() {
+        let config = config(1, DEFAULT_MAX_AGE_MS, &[]);
+        let report = network_error("https://example.com", 0, "application");
+
+        assert!(config.is_allowed_type(&report));
+    }
+
+    #[test]
+    fn rejects_types_not_on_the_allow_list() {
+        let config = config(1, DEFAULT_MAX_AGE_MS, &["csp-violation"]);
+        let report = network_error("https://example.com", 0, "application");
+
+        assert!(!config.is_allowed_type(&report));
+    }
+
+    #[test]
+    fn keeps_reports_at_exactly_the_max_age() {
+        let config = config(1, 180_000, &[]);
+        let report = network_error("https://example.com", 180_000, "application");
+
+        assert!(config.is_within_max_age(&report));
+    }
+
+    #[test]
+    fn drops_reports_older_than_the_max_age() {
+        let config = config(1, 180_000, &[]);
+        let report = network_error("https://example.com", 180_001, "application");
+
+        assert!(!config.is_within_max_age(&report));
+    }
+
+    #[test]
+    fn sample_rate_of_one_keeps_every_report() {
+        let config = config(1, DEFAULT_MAX_AGE_MS, &[]);
+
+        for i in 0..50 {
+            let report = network_error(&format!("https://example.com/{}", i), 0, "application");
+            assert!(config.is_sampled(&report));
+        }
+    }
+
+    #[test]
+    fn sampling_is_deterministic_for_the_same_url_type_and_phase() {
+        let config = config(10, DEFAULT_MAX_AGE_MS, &[]);
+        let report = network_error("https://example.com/flaky", 0, "application");
+
+        let first = config.is_sampled(&report);
+        for _ in 0..10 {
+            assert_eq!(config.is_sampled(&report), first);
+        }
+    }
+
+    #[test]
+    fn sampling_can_differ_for_different_urls() {
+        let config = config(2, DEFAULT_MAX_AGE_MS, &[]);
+
+        let kept_at_least_one = (0..20)
+            .map(|i| network_error(&format!("https://example.com/{}", i), 0, "application"))
+            .any(|report| config.is_sampled(&report));
+        let dropped_at_least_one = (0..20)
+            .map(|i| network_error(&format!("https://example.com/{}", i), 0, "application"))
+            .any(|report| !config.is_sampled(&report));
+
+        assert!(kept_at_least_one);
+        assert!(dropped_at_least_one);
+    }
+}
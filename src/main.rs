@@ -1,19 +1,24 @@
-//! Compute@Edge starter kit for Network Error Logging
+//! Compute@Edge starter kit for the W3C Reporting API
 //!
 //! A Compute@Edge service which exposes a HTTP reporting endpoint for the
-//! W3C [Network Error Logging API][specification].
+//! W3C [Reporting API][specification], covering Network Error Logging
+//! reports as well as CSP violation, deprecation, intervention and crash
+//! reports.
 //!
-//! [specification]: https://www.w3.org/TR/network-error-logging
+//! [specification]: https://www.w3.org/TR/reporting-1/
 use chrono::Utc;
 use fastly::http::{header, Method, StatusCode};
 use fastly::log::Endpoint;
 use fastly::{downstream_client_ip_addr, Body, Error, Request, Response, ResponseExt};
 use serde::{Deserialize, Serialize};
 use std::io::Write;
+use std::net::IpAddr;
 
 // Import the `Report` and `ClientData` data structures.
 mod client_data;
+mod filter;
 mod report;
+mod routing;
 
 use crate::client_data::ClientData;
 use crate::report::Report;
@@ -38,56 +43,125 @@ fn main(req: Request<Body>) -> Result<Response<Body>, Error> {
     }
 }
 
+/// Media types we accept reports in.
+///
+/// `application/csp-report` is deliberately not included: that's the
+/// legacy `report-uri` envelope, a single `{"csp-report": {...}}` object
+/// with hyphenated keys rather than a Reporting API `Vec<Report>` array,
+/// and we don't parse that shape. Accepting the content type without
+/// understanding the body would just route all of that traffic to
+/// `rejects` instead of rejecting it with `415` up front.
+const ACCEPTED_CONTENT_TYPES: &[&str] = &["application/reports+json", "application/json"];
+
 /// Handle reports.
 ///
-/// It attempts to extract the NEL reports from the POST request body and maps
-/// over each report adding additional information before emitting a log line
-/// to the `reports` logging endpoint if valid. It always returns a synthetic
-/// `204 No content` response, regardless of whether the log reporting was
-/// successful.
+/// It validates the request's Content-Type, then attempts to extract the
+/// reports from the POST body and maps over each report adding additional
+/// information before emitting a log line to the logging endpoint its
+/// report type is routed to (`reports` by default) if valid. An unsupported Content-Type is rejected with `415`.
+/// A supported Content-Type whose body fails to parse is logged to the
+/// `rejects` endpoint and rejected with `400`, instead of entering the
+/// report pipeline. Only a successfully-parsed batch gets the synthetic
+/// `204 No Content` response.
 fn handle_reports(req: Request<Body>) -> Result<Response<Body>, Error> {
     let (parts, body) = req.into_parts();
 
-    // Parse the NEL reports from the request JSON body using serde_json.
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|header| header.to_str().ok())
+        .unwrap_or("")
+        .to_owned();
+
+    // Strip any `; charset=...` parameter before matching the media type.
+    let media_type = content_type.split(';').next().unwrap_or("").trim();
+    if !ACCEPTED_CONTENT_TYPES.contains(&media_type) {
+        return Ok(Response::builder()
+            .status(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+            .body(Body::from("Unsupported content type"))?);
+    }
+
+    let client_ip = downstream_client_ip_addr().expect("should have client IP");
+    let body_bytes = body.into_bytes();
+
+    // Parse the reports from the request JSON body using serde_json.
     // If successful, bind the reports to the `reports` variable, transform and log.
-    if let Ok(reports) = serde_json::from_reader::<Body, Vec<Report>>(body) {
-        // Extract information about the client from the downstream request,
-        // such as the User-Agent and IP address.
-        let client_user_agent = parts
-            .headers
-            .get(header::USER_AGENT)
-            .and_then(|header| header.to_str().ok())
-            .unwrap_or("");
-        let client_ip = downstream_client_ip_addr().expect("should have client IP");
-
-        // Construct a new `ClientData` structure from the IP and User Agent.
-        let client_data = ClientData::new(client_ip, client_user_agent)?;
-
-        // Generate a list of reports to be logged by mapping over each raw NEL
-        // report, merging it with the `ClientData` from above and transform it
-        // to a `LogLine`.
-        let logs: Vec<LogLine> = reports
-            .into_iter()
-            .map(|report| LogLine::new(report, client_data.clone()))
-            .filter_map(Result::ok)
-            .collect();
-
-        // Create a handle to the upstream logging endpoint that we want to emit
-        // the reports too.
-        let mut endpoint = Endpoint::from_name("reports");
-
-        // Loop over each log line serializing it back to JSON and write it to
-        // the logging endpoint.
-        for log in logs.iter() {
-            if let Ok(json) = serde_json::to_string(&log) {
-                writeln!(endpoint, "{}", json)?;
-            }
+    match serde_json::from_slice::<Vec<Report>>(&body_bytes) {
+        Ok(reports) => {
+            // Drop stale, disallowed or sampled-out reports before we do
+            // any further enrichment on them.
+            let reports = filter::filter_reports(reports);
+
+            // Extract information about the client from the downstream request,
+            // such as the User-Agent and IP address.
+            let client_user_agent = parts
+                .headers
+                .get(header::USER_AGENT)
+                .and_then(|header| header.to_str().ok())
+                .unwrap_or("");
+
+            // Construct a new `ClientData` structure from the IP and User Agent.
+            let client_data = ClientData::new(client_ip, client_user_agent)?;
+
+            // Generate a list of reports to be logged by mapping over each raw
+            // report, merging it with the `ClientData` from above and transform it
+            // to a `LogLine`.
+            let logs: Vec<LogLine> = reports
+                .into_iter()
+                .map(|report| LogLine::new(report, client_data.clone()))
+                .filter_map(Result::ok)
+                .collect();
+
+            // Route each log line to its logging endpoint by report type,
+            // fanning the batch out across endpoints in one pass.
+            routing::route_logs(&logs)?;
+
+            // Return an empty 204 no content response to the downstream client.
+            generate_no_content_response()
         }
+        Err(err) => {
+            log_reject(&media_type, body_bytes.len(), client_ip, &err.to_string())?;
+
+            Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from("Malformed report batch"))?)
+        }
+    }
+}
+
+/// `RejectLine` models a batch we couldn't parse as reports.
+///
+/// Emitted to the `rejects` logging endpoint so operators can see how
+/// much traffic is being dropped and why, without it polluting the
+/// `reports` pipeline.
+#[derive(Serialize)]
+struct RejectLine<'a> {
+    /// A unix timestamp generated when we receive the batch.
+    timestamp: i64,
+    /// The client's IP address.
+    client_ip: IpAddr,
+    /// The request's Content-Type media type (parameters stripped).
+    content_type: &'a str,
+    /// The size of the rejected body, in bytes.
+    body_bytes: usize,
+    /// Why the body failed to parse.
+    reason: &'a str,
+}
+
+/// Emit a `RejectLine` to the `rejects` logging endpoint.
+fn log_reject(content_type: &str, body_bytes: usize, client_ip: IpAddr, reason: &str) -> Result<(), Error> {
+    let line = RejectLine {
+        timestamp: Utc::now().timestamp(),
+        client_ip,
+        content_type,
+        body_bytes,
+        reason,
     };
 
-    // Return and empty 204 no content response to the downstream client,
-    // regardless of successful logging.
-    generate_no_content_response()
+    let mut endpoint = Endpoint::from_name("rejects");
+    writeln!(endpoint, "{}", serde_json::to_string(&line)?)?;
+
+    Ok(())
 }
 
 /// `LogLine` models the structure of a log line.
@@ -102,6 +176,11 @@ pub struct LogLine {
     timestamp: i64,
     // The GeoIP client data.
     client: ClientData,
+    /// The report type discriminator, copied up from `report` so that
+    /// downstream tables (e.g. BigQuery) can partition on it without
+    /// unpacking the report body.
+    #[serde(rename = "type")]
+    report_type: String,
     /// The sanitized report.
     report: Report,
 }
@@ -113,9 +192,16 @@ impl LogLine {
         Ok(LogLine {
             timestamp: Utc::now().timestamp(),
             client,
+            report_type: report.report_type.clone(),
             report,
         })
     }
+
+    /// The report type discriminator, used to route this line to its
+    /// logging endpoint.
+    pub fn report_type(&self) -> &str {
+        &self.report_type
+    }
 }
 
 /// Utility to generate a synthetic `204 No Content` response.
@@ -0,0 +1,302 @@
+//! Report envelope and body types.
+//!
+//! A report POSTed to the reporting endpoint follows the [Reporting API]
+//! envelope: a common set of fields (`age`, `type`, `url`, `user_agent`)
+//! wrapping a `body` whose shape depends on `type`. Network Error Logging
+//! is just one of the report types a browser may send; others include CSP
+//! violations, deprecation notices, interventions and crash reports.
+//!
+//! [Reporting API]: https://www.w3.org/TR/reporting-1/
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use serde_json::Value;
+use std::io::Write;
+
+/// A single report from the Reporting API envelope.
+///
+/// `body` is dispatched on the envelope's `type` field into the matching
+/// [`ReportBody`] variant, mirroring how the `reporting-api` crate models
+/// these. Types we don't recognise (or whose body fails to parse) fall back
+/// to [`ReportBody::Unknown`] so nothing is silently dropped.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// How long ago, in milliseconds, the event that caused this report
+    /// occurred.
+    pub age: i64,
+    /// The report type discriminator, e.g. `"network-error"` or
+    /// `"csp-violation"`.
+    #[serde(rename = "type")]
+    pub report_type: String,
+    /// The URL of the document or worker that generated the report.
+    pub url: String,
+    /// The User-Agent string of the browser that generated the report.
+    pub user_agent: String,
+    /// The type-specific report body.
+    pub body: ReportBody,
+}
+
+impl<'de> Deserialize<'de> for Report {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct RawReport {
+            age: i64,
+            #[serde(rename = "type")]
+            report_type: String,
+            url: String,
+            user_agent: String,
+            body: Value,
+        }
+
+        let raw = RawReport::deserialize(deserializer)?;
+        let body = ReportBody::parse(&raw.report_type, raw.body);
+
+        Ok(Report {
+            age: raw.age,
+            report_type: raw.report_type,
+            url: raw.url,
+            user_agent: raw.user_agent,
+            body,
+        })
+    }
+}
+
+/// The type-specific payload of a [`Report`].
+#[derive(Debug, Clone)]
+pub enum ReportBody {
+    NetworkError(NetworkErrorBody),
+    CspViolation(CspViolationBody),
+    Deprecation(DeprecationBody),
+    Intervention(InterventionBody),
+    Crash(CrashBody),
+    /// A report type we don't model explicitly, kept as raw JSON so it's
+    /// never dropped on the floor.
+    Unknown(Value),
+}
+
+/// Serialize a `ReportBody` as the inner variant's own fields, flat,
+/// rather than serde's default externally-tagged `{"NetworkError": {...}}`
+/// wrapper. This keeps the logged `body` object shaped the same way it
+/// was before the envelope refactor (and the way `reporting-api`-style
+/// consumers expect it), with the envelope's own `type` field as the only
+/// discriminator.
+impl Serialize for ReportBody {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            ReportBody::NetworkError(body) => body.serialize(serializer),
+            ReportBody::CspViolation(body) => body.serialize(serializer),
+            ReportBody::Deprecation(body) => body.serialize(serializer),
+            ReportBody::Intervention(body) => body.serialize(serializer),
+            ReportBody::Crash(body) => body.serialize(serializer),
+            ReportBody::Unknown(value) => value.serialize(serializer),
+        }
+    }
+}
+
+impl ReportBody {
+    /// Parse a report `body` according to the envelope's `type`, falling
+    /// back to the raw value if the type is unrecognised or the body
+    /// doesn't match the shape we expect for it. A known type whose body
+    /// fails to parse is logged to the `rejects` endpoint, since that's a
+    /// sign our structs have drifted from the spec (or the sender's
+    /// implementation of it) rather than an expected unknown type.
+    fn parse(report_type: &str, body: Value) -> ReportBody {
+        let parsed = match report_type {
+            "network-error" => serde_json::from_value(body.clone()).map(ReportBody::NetworkError),
+            "csp-violation" => serde_json::from_value(body.clone()).map(ReportBody::CspViolation),
+            "deprecation" => serde_json::from_value(body.clone()).map(ReportBody::Deprecation),
+            "intervention" => serde_json::from_value(body.clone()).map(ReportBody::Intervention),
+            "crash" => serde_json::from_value(body.clone()).map(ReportBody::Crash),
+            _ => return ReportBody::Unknown(body),
+        };
+
+        parsed.unwrap_or_else(|err| {
+            log_body_mismatch(report_type, &body, &err);
+            ReportBody::Unknown(body)
+        })
+    }
+}
+
+/// Log a known report type whose body didn't match the struct we expect
+/// for it, so a spec drift (or a renamed/typo'd field on our side) shows
+/// up in the `rejects` endpoint instead of silently degrading every such
+/// report to `ReportBody::Unknown`.
+fn log_body_mismatch(report_type: &str, body: &Value, err: &serde_json::Error) {
+    let mut endpoint = fastly::log::Endpoint::from_name("rejects");
+    let _ = writeln!(
+        endpoint,
+        "{}",
+        serde_json::json!({
+            "reason": "known report type failed body parse",
+            "type": report_type,
+            "error": err.to_string(),
+            "body": body,
+        })
+    );
+}
+
+/// Body of a Network Error Logging report.
+///
+/// See the [NEL specification](https://www.w3.org/TR/network-error-logging/#dfn-nel-response-body-member).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkErrorBody {
+    pub referrer: String,
+    pub sampling_fraction: f64,
+    pub server_ip: String,
+    pub protocol: String,
+    pub method: String,
+    pub status_code: u16,
+    pub elapsed_time: u64,
+    pub phase: String,
+    /// The failure category, e.g. `"tcp.refused"` or `"dns.unreachable"`.
+    #[serde(rename = "type")]
+    pub failure_type: String,
+}
+
+/// Body of a CSP violation report.
+///
+/// See the [CSP3 specification](https://www.w3.org/TR/CSP3/#reporting).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CspViolationBody {
+    #[serde(rename = "blockedURL")]
+    pub blocked_url: Option<String>,
+    pub disposition: String,
+    #[serde(rename = "documentURL")]
+    pub document_url: String,
+    #[serde(rename = "effectiveDirective")]
+    pub effective_directive: String,
+    #[serde(rename = "originalPolicy")]
+    pub original_policy: String,
+    pub referrer: Option<String>,
+    pub sample: Option<String>,
+    #[serde(rename = "statusCode")]
+    pub status_code: u16,
+    #[serde(rename = "violatedDirective")]
+    pub violated_directive: String,
+}
+
+/// Body of a deprecation report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeprecationBody {
+    pub id: String,
+    pub message: String,
+    #[serde(rename = "anticipatedRemoval")]
+    pub anticipated_removal: Option<String>,
+    #[serde(rename = "sourceFile")]
+    pub source_file: Option<String>,
+    #[serde(rename = "lineNumber")]
+    pub line_number: Option<u32>,
+    #[serde(rename = "columnNumber")]
+    pub column_number: Option<u32>,
+}
+
+/// Body of a browser intervention report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterventionBody {
+    pub id: String,
+    pub message: String,
+    #[serde(rename = "sourceFile")]
+    pub source_file: Option<String>,
+    #[serde(rename = "lineNumber")]
+    pub line_number: Option<u32>,
+    #[serde(rename = "columnNumber")]
+    pub column_number: Option<u32>,
+}
+
+/// Body of a crash report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashBody {
+    pub reason: Option<String>,
+    pub is_top_level: Option<bool>,
+    pub visibility_state: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network_error_report() -> Report {
+        Report {
+            age: 10,
+            report_type: "network-error".to_string(),
+            url: "https://example.com/".to_string(),
+            user_agent: "test-agent".to_string(),
+            body: ReportBody::NetworkError(NetworkErrorBody {
+                referrer: "https://example.com/".to_string(),
+                sampling_fraction: 1.0,
+                server_ip: "127.0.0.1".to_string(),
+                protocol: "h2".to_string(),
+                method: "GET".to_string(),
+                status_code: 200,
+                elapsed_time: 50,
+                phase: "application".to_string(),
+                failure_type: "tcp.refused".to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn report_body_serializes_flat_not_externally_tagged() {
+        let json = serde_json::to_value(network_error_report()).unwrap();
+        let body = json.get("body").unwrap();
+
+        // The old NEL-only shape had `body.referrer` directly; serde's
+        // default enum tagging would instead produce
+        // `body.NetworkError.referrer`, breaking every downstream
+        // consumer of the logged JSON.
+        assert_eq!(body.get("referrer").unwrap(), "https://example.com/");
+        assert!(body.get("NetworkError").is_none());
+    }
+
+    #[test]
+    fn deserializes_network_error_report() {
+        let raw = serde_json::json!({
+            "age": 10,
+            "type": "network-error",
+            "url": "https://example.com/",
+            "user_agent": "test-agent",
+            "body": {
+                "referrer": "https://example.com/",
+                "sampling_fraction": 1.0,
+                "server_ip": "127.0.0.1",
+                "protocol": "h2",
+                "method": "GET",
+                "status_code": 200,
+                "elapsed_time": 50,
+                "phase": "application",
+                "type": "ok"
+            }
+        });
+
+        let report: Report = serde_json::from_value(raw).unwrap();
+
+        match report.body {
+            ReportBody::NetworkError(body) => assert_eq!(body.failure_type, "ok"),
+            other => panic!("expected NetworkError body, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unrecognised_type_falls_back_to_raw_value() {
+        let raw = serde_json::json!({
+            "age": 10,
+            "type": "some-future-type",
+            "url": "https://example.com/",
+            "user_agent": "test-agent",
+            "body": {"foo": "bar"}
+        });
+
+        let report: Report = serde_json::from_value(raw).unwrap();
+
+        match report.body {
+            ReportBody::Unknown(value) => assert_eq!(value["foo"], "bar"),
+            other => panic!("expected Unknown body, got {:?}", other),
+        }
+    }
+}